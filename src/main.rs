@@ -3,14 +3,20 @@ extern crate lazy_static;
 #[macro_use]
 extern crate prometheus;
 
+use std::collections::hash_map::DefaultHasher;
 use std::env;
+use std::hash::{Hash, Hasher};
 use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use axum::prelude::*;
+use cached::{Cached, TimedCache};
 use http::status::StatusCode;
 use log::{debug, error, info, warn};
-use prometheus::{Encoder, GaugeVec, IntGaugeVec, TextEncoder};
+use prometheus::{Encoder, GaugeVec, IntGauge, IntGaugeVec, TextEncoder};
 use serde::{self, Deserialize};
+use thiserror::Error;
 
 lazy_static! {
     static ref LAST_SEEN_TIMESTAMP: IntGaugeVec = register_int_gauge_vec!(
@@ -43,6 +49,18 @@ lazy_static! {
         &["id", "sensor_label"]
     )
     .unwrap();
+    static ref PM2_5_CORRECTED_VALUE: GaugeVec = register_gauge_vec!(
+        "purpleair_pm2_5_corrected_value",
+        "EPA humidity-corrected PM2.5 particulate mass in ug/m3",
+        &["id", "sensor_label"]
+    )
+    .unwrap();
+    static ref PM2_5_CORRECTED_AQI: IntGaugeVec = register_int_gauge_vec!(
+        "purpleair_pm2_5_corrected_aqi_estimate",
+        "Estimated instantaneous PM2.5 AQI value computed from the EPA humidity-corrected value",
+        &["id", "sensor_label"]
+    )
+    .unwrap();
     static ref PARTICULATE_MASS: GaugeVec = register_gauge_vec!(
         "purpleair_",
         "Sensor reported raw value particulate mass in ug/m3",
@@ -67,9 +85,135 @@ lazy_static! {
         &["id", "sensor_label"]
     )
     .unwrap();
+    static ref SCRAPE_SUCCESS: IntGauge = register_int_gauge!(
+        "purpleair_scrape_success",
+        "1 if the most recent background scrape succeeded, 0 otherwise"
+    )
+    .unwrap();
+    static ref LAST_SCRAPE_TIMESTAMP: IntGauge = register_int_gauge!(
+        "purpleair_last_scrape_timestamp",
+        "UTC timestamp of the most recent background scrape attempt"
+    )
+    .unwrap();
+    static ref SCRAPE_ERRORS: IntGaugeVec = register_int_gauge_vec!(
+        "purpleair_scrape_errors_total",
+        "Count of per-sensor scrape errors, labeled by sensor id (local host name for local sensors, \"unknown\" for unparseable results) and failure reason",
+        &["id", "reason"]
+    )
+    .unwrap();
+    static ref CHANNEL_AGREEMENT: GaugeVec = register_gauge_vec!(
+        "purpleair_channel_agreement",
+        "Percent difference between a sensor's A and B channel pm2_5_cf_1 readings",
+        &["id", "sensor_label"]
+    )
+    .unwrap();
+    static ref PM2_5_VALID: IntGaugeVec = register_int_gauge_vec!(
+        "purpleair_pm2_5_valid",
+        "1 if a sensor's A/B channels agree within the configured threshold, 0 if they diverge",
+        &["id", "sensor_label"]
+    )
+    .unwrap();
+    static ref SCRAPE_CACHE: Mutex<TimedCache<String, Vec<SensorInfo>>> =
+        Mutex::new(TimedCache::with_lifespan(cache_ttl_seconds()));
 }
 
-#[derive(Deserialize, Debug)]
+/// Reads the upstream response cache TTL from `PURPLEAIR_CACHE_TTL_SECONDS`, defaulting to 60
+/// seconds when unset or unparseable. The background refresher (`run_scrape_refresher`) fetches
+/// through this cache too, so a refresh that comes within the TTL of the last upstream fetch
+/// reuses that response instead of contacting PurpleAir.
+fn cache_ttl_seconds() -> u64 {
+    env::var("PURPLEAIR_CACHE_TTL_SECONDS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(60)
+}
+
+/// Max allowed percent difference between a sensor's A/B channel `pm2_5_cf_1` readings before
+/// `PM2_5_VALID` is flagged, read from `PURPLEAIR_CHANNEL_AGREEMENT_THRESHOLD_PERCENT`.
+fn channel_agreement_threshold_percent() -> f64 {
+    env::var("PURPLEAIR_CHANNEL_AGREEMENT_THRESHOLD_PERCENT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(30f64)
+}
+
+/// Pairs each A channel sensor with its B channel (matched via `parent_id`) and records how well
+/// the two agree, so downstream alerting can ignore a sensor whose channels have diverged.
+fn update_channel_agreement(sensor_infos: &[SensorInfo]) {
+    for primary in sensor_infos.iter().filter(|s| s.parent_id.is_none()) {
+        let common_labels: &[&str] = &[&primary.id_string(), &primary.label];
+        let secondary = match sensor_infos.iter().find(|s| s.parent_id == Some(primary.id)) {
+            Some(secondary) => secondary,
+            None => {
+                PM2_5_VALID.with_label_values(common_labels).set(1);
+                continue;
+            }
+        };
+
+        let (a, b) = match (
+            primary.pm2_5_cf_1.parse::<f64>(),
+            secondary.pm2_5_cf_1.parse::<f64>(),
+        ) {
+            (Ok(a), Ok(b)) => (a, b),
+            _ => {
+                warn!(
+                    "could not parse pm2_5_cf_1 for channel agreement on sensor {}",
+                    primary.id_string()
+                );
+                continue;
+            }
+        };
+
+        let percent_diff = channel_percent_diff(a, b);
+        CHANNEL_AGREEMENT
+            .with_label_values(common_labels)
+            .set(percent_diff);
+        PM2_5_VALID
+            .with_label_values(common_labels)
+            .set((percent_diff <= channel_agreement_threshold_percent()) as i64);
+    }
+}
+
+/// Percent difference between a sensor's two channel readings, relative to their average.
+/// An average of 0 is treated as perfect agreement rather than dividing by zero.
+fn channel_percent_diff(a: f64, b: f64) -> f64 {
+    let average = (a + b) / 2f64;
+    if average == 0f64 {
+        0f64
+    } else {
+        ((a - b).abs() / average) * 100f64
+    }
+}
+
+/// Errors encountered while fetching and parsing a PurpleAir response. `FieldParse` errors are
+/// isolated to the offending sensor so one bad result doesn't blank out the rest of the scrape.
+#[derive(Error, Debug)]
+enum ScrapeError {
+    #[error("request to PurpleAir failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("failed to parse PurpleAir response: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("sensor {sensor_id}: failed to parse field `{field}`")]
+    FieldParse { sensor_id: String, field: &'static str },
+}
+
+fn parse_f64(sensor_id: &str, field: &'static str, value: &str) -> Result<f64, ScrapeError> {
+    value.parse().map_err(|_| ScrapeError::FieldParse {
+        sensor_id: sensor_id.to_string(),
+        field,
+    })
+}
+
+fn parse_i64(sensor_id: &str, field: &'static str, value: &str) -> Result<i64, ScrapeError> {
+    value.parse().map_err(|_| ScrapeError::FieldParse {
+        sensor_id: sensor_id.to_string(),
+        field,
+    })
+}
+
+#[derive(Deserialize, Debug, Clone)]
 struct SensorInfo {
     #[serde(rename = "ID")]
     id: i64,
@@ -83,6 +227,10 @@ struct SensorInfo {
     #[serde(rename = "Lon")]
     lon: f64,
 
+    // Present on a sensor's B channel result and set to the A channel's ID; absent on A channel.
+    #[serde(rename = "ParentID")]
+    parent_id: Option<i64>,
+
     #[serde(rename = "PM2_5Value")]
     pm_2_5_value: String,
 
@@ -123,6 +271,13 @@ impl SensorInfo {
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
 
+    let sensor_ids = env::var("PURPLEAIR_SENSOR_IDS").ok();
+    let local_hosts = local_hosts();
+    if sensor_ids.is_none() && local_hosts.is_empty() {
+        warn!("neither PURPLEAIR_SENSOR_IDS nor PURPLEAIR_LOCAL_HOSTS is set; no sensors will be scraped");
+    }
+    tokio::spawn(run_scrape_refresher(sensor_ids, local_hosts, scrape_interval()));
+
     let app = route("/metrics", get(metrics));
 
     let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
@@ -132,7 +287,74 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-async fn scrape_purple_air(sensor_ids: &str) -> Result<(), Box<dyn std::error::Error>> {
+/// How often the background refresher runs, read from `PURPLEAIR_SCRAPE_INTERVAL_SECONDS` and
+/// defaulting to 60 seconds. Cloud API fetches go through `SCRAPE_CACHE`, so PurpleAir itself is
+/// only contacted once the cached response is older than the cache TTL.
+fn scrape_interval() -> Duration {
+    let secs = env::var("PURPLEAIR_SCRAPE_INTERVAL_SECONDS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(60);
+    Duration::from_secs(secs)
+}
+
+/// Polls the cloud API (if `sensor_ids` is set) and any configured local sensors on `interval`,
+/// updating the registered gauges so `/metrics` never has to wait on (or fail because of) either.
+async fn run_scrape_refresher(sensor_ids: Option<String>, local_hosts: Vec<String>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let mut success = true;
+
+        if let Some(ref sensor_ids) = sensor_ids {
+            if let Err(e) = scrape_purple_air(sensor_ids).await {
+                error!("background scrape failed: {:?}", e);
+                success = false;
+            }
+        }
+        if !local_hosts.is_empty() {
+            if let Err(e) = scrape_local_sensors(&local_hosts).await {
+                error!("local sensor scrape failed: {:?}", e);
+                success = false;
+            }
+        }
+
+        SCRAPE_SUCCESS.set(success as i64);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        LAST_SCRAPE_TIMESTAMP.set(now);
+    }
+}
+
+/// Maps a parsed cloud API response document (`{"results": [...]}`, one entry per channel) to
+/// sensor readings.
+fn sensor_infos_from_cloud_document(document: &serde_json::Value) -> Vec<SensorInfo> {
+    let parse_one = |result: &serde_json::Value| match serde_json::from_value::<SensorInfo>(result.clone()) {
+        Ok(sensor_info) => Some(sensor_info),
+        Err(e) => {
+            warn!("dropping unparseable sensor result: {:?}", e);
+            SCRAPE_ERRORS.with_label_values(&["unknown", "json"]).inc();
+            None
+        }
+    };
+
+    match document.get("results").and_then(|results| results.as_array()) {
+        Some(results_arr) => results_arr.iter().filter_map(parse_one).collect(),
+        None => vec![],
+    }
+}
+
+/// Fetches the sensor readings for `sensor_ids` from the cloud API, reusing the last response
+/// from `SCRAPE_CACHE` when it is still within its TTL instead of hitting the upstream API again.
+async fn fetch_sensor_infos(sensor_ids: &str) -> Result<Vec<SensorInfo>, ScrapeError> {
+    let cache_key = sensor_ids.to_string();
+    if let Some(cached) = SCRAPE_CACHE.lock().unwrap().cache_get(&cache_key) {
+        debug!("serving {} sensor(s) from cache", cached.len());
+        return Ok(cached.clone());
+    }
+
     let purple_air_resp: serde_json::Value = reqwest::get(format!(
         "https://www.purpleair.com/json?show={}",
         sensor_ids.replace(',', "|")
@@ -142,63 +364,267 @@ async fn scrape_purple_air(sensor_ids: &str) -> Result<(), Box<dyn std::error::E
     .await?;
     debug!("resp = {:?}", purple_air_resp);
 
-    match purple_air_resp.get("results") {
-        Some(results) => {
-            if let Some(results_arr) = results.as_array() {
-                for result in results_arr {
-                    let sensor_info: SensorInfo = serde_json::from_value(result.clone())?;
-                    let common_labels: &[&str] = &[&sensor_info.id_string(), &sensor_info.label];
-                    if let Some(ref uptime) = sensor_info.uptime {
-                        UPTIME
-                            .with_label_values(&common_labels)
-                            .set(uptime.parse::<i64>()?);
-                    }
-                    LAST_SEEN_TIMESTAMP
-                        .with_label_values(common_labels)
-                        .set(sensor_info.last_seen);
-                    INFO.with_label_values(&[
-                        &sensor_info.id_string(),
-                        &sensor_info.label,
-                        format!("{}", sensor_info.lat).as_str(),
-                        format!("{}", sensor_info.lon).as_str(),
-                    ])
-                    .set(1);
-
-                    PM2_5_VALUE
-                        .with_label_values(common_labels)
-                        .set(sensor_info.pm_2_5_value.parse::<f64>()?);
-                    PM2_5_AQI
-                        .with_label_values(common_labels)
-                        .set(pm2_5_aqi_estimate(sensor_info.pm_2_5_value.parse::<f64>()?) as i64);
-
-                    if let Some(temp_f) = sensor_info.temp_f {
-                        TEMP.with_label_values(common_labels)
-                            .set(temp_f.parse::<f64>()?);
-                    }
-                    if let Some(humidity) = sensor_info.humidity {
-                        HUMIDITY
+    if purple_air_resp.get("results").is_none() {
+        warn!("results array not found!");
+    }
+    let sensor_infos = sensor_infos_from_cloud_document(&purple_air_resp);
+    SCRAPE_CACHE
+        .lock()
+        .unwrap()
+        .cache_set(cache_key, sensor_infos.clone());
+    Ok(sensor_infos)
+}
+
+/// Hostnames/IPs of LAN-connected PurpleAir devices to poll directly, from the comma-separated
+/// `PURPLEAIR_LOCAL_HOSTS` env var.
+fn local_hosts() -> Vec<String> {
+    env::var("PURPLEAIR_LOCAL_HOSTS")
+        .ok()
+        .map(|hosts| {
+            hosts
+                .split(',')
+                .map(|host| host.trim().to_string())
+                .filter(|host| !host.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Shape of a LAN-connected PurpleAir device's on-device `/json` document. Unlike the cloud API
+/// this reports both channels in a single flat, mostly lowercase-keyed document (channel B readings are
+/// suffixed `_b`) and uses native JSON numbers rather than stringified values, so it's parsed into
+/// its own struct and converted into `SensorInfo` values rather than reusing the cloud shape.
+#[derive(Deserialize, Debug)]
+struct LocalSensorDocument {
+    #[serde(rename = "SensorId")]
+    sensor_id: Option<String>,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    current_temp_f: Option<f64>,
+    current_humidity: Option<f64>,
+    pressure: Option<f64>,
+    pm2_5_cf_1: Option<f64>,
+    pm2_5_atm: Option<f64>,
+    pm2_5_cf_1_b: Option<f64>,
+    pm2_5_atm_b: Option<f64>,
+}
+
+/// Derives a stable synthetic sensor id from a local hostname, since local documents don't carry
+/// the cloud API's numeric `ID`. Kept negative so it can't collide with a real cloud sensor id.
+fn synth_local_sensor_id(host: &str, channel_offset: i64) -> i64 {
+    let mut hasher = DefaultHasher::new();
+    host.hash(&mut hasher);
+    -(((hasher.finish() % 1_000_000) as i64) + channel_offset)
+}
+
+/// Builds a `SensorInfo` for one channel of a local document, filling in the cloud-only fields
+/// that `update_gauges_for_sensor`/channel-agreement logic don't actually read with harmless
+/// placeholders.
+fn local_channel_sensor_info(
+    id: i64,
+    label: String,
+    parent_id: Option<i64>,
+    lat: f64,
+    lon: f64,
+    last_seen: i64,
+    pm_2_5_value: f64,
+    pm2_5_cf_1: f64,
+    temp_f: Option<f64>,
+    humidity: Option<f64>,
+    pressure: Option<f64>,
+) -> SensorInfo {
+    SensorInfo {
+        id,
+        label,
+        lat,
+        lon,
+        parent_id,
+        pm_2_5_value: pm_2_5_value.to_string(),
+        uptime: None,
+        last_seen,
+        p_0_3_um: "0".to_string(),
+        p_0_5_um: "0".to_string(),
+        p_1_0_um: "0".to_string(),
+        p_2_5_um: "0".to_string(),
+        p_5_0_um: "0".to_string(),
+        p_10_0_um: "0".to_string(),
+        pm1_0_cf_1: "0".to_string(),
+        pm2_5_cf_1: pm2_5_cf_1.to_string(),
+        pm10_0_cf_1: "0".to_string(),
+        pm1_0_atm: "0".to_string(),
+        pm2_5_atm: pm_2_5_value.to_string(),
+        pm10_0_atm: "0".to_string(),
+        temp_f: temp_f.map(|v| v.to_string()),
+        humidity: humidity.map(|v| v.to_string()),
+        pressure: pressure.map(|v| v.to_string()),
+    }
+}
+
+/// Converts a local device document into one `SensorInfo` per channel it reports, pairing a B
+/// channel (if present) to the A channel via `parent_id` so channel-agreement logic still applies.
+fn sensor_infos_from_local_document(host: &str, doc: &LocalSensorDocument) -> Vec<SensorInfo> {
+    let last_seen = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let label = doc.sensor_id.clone().unwrap_or_else(|| host.to_string());
+    let id = synth_local_sensor_id(host, 0);
+
+    let mut sensor_infos = vec![local_channel_sensor_info(
+        id,
+        label.clone(),
+        None,
+        doc.lat.unwrap_or(0f64),
+        doc.lon.unwrap_or(0f64),
+        last_seen,
+        doc.pm2_5_atm.unwrap_or(0f64),
+        doc.pm2_5_cf_1.unwrap_or(0f64),
+        doc.current_temp_f,
+        doc.current_humidity,
+        doc.pressure,
+    )];
+
+    if let Some(pm2_5_cf_1_b) = doc.pm2_5_cf_1_b {
+        sensor_infos.push(local_channel_sensor_info(
+            synth_local_sensor_id(host, 1),
+            format!("{} B", label),
+            Some(id),
+            doc.lat.unwrap_or(0f64),
+            doc.lon.unwrap_or(0f64),
+            last_seen,
+            doc.pm2_5_atm_b.unwrap_or(0f64),
+            pm2_5_cf_1_b,
+            None,
+            None,
+            None,
+        ));
+    }
+
+    sensor_infos
+}
+
+/// Polls each configured local PurpleAir device's on-device `/json` endpoint directly, so the
+/// exporter can run fully offline without the cloud API.
+async fn scrape_local_sensors(hosts: &[String]) -> Result<(), ScrapeError> {
+    for host in hosts {
+        let resp = match reqwest::get(format!("http://{}/json", host)).await {
+            Ok(resp) => resp,
+            Err(e) => {
+                warn!("failed to reach local sensor {}: {:?}", host, e);
+                SCRAPE_ERRORS.with_label_values(&[host, "http"]).inc();
+                continue;
+            }
+        };
+        let doc: LocalSensorDocument = match resp.json().await {
+            Ok(doc) => doc,
+            Err(e) => {
+                warn!("failed to parse response from local sensor {}: {:?}", host, e);
+                SCRAPE_ERRORS.with_label_values(&[host, "json"]).inc();
+                continue;
+            }
+        };
+        let sensor_infos = sensor_infos_from_local_document(host, &doc);
+        for sensor_info in &sensor_infos {
+            update_gauges_for_sensor(sensor_info);
+        }
+        update_channel_agreement(&sensor_infos);
+    }
+    Ok(())
+}
+
+async fn scrape_purple_air(sensor_ids: &str) -> Result<(), ScrapeError> {
+    let sensor_infos = fetch_sensor_infos(sensor_ids).await?;
+    for sensor_info in &sensor_infos {
+        update_gauges_for_sensor(sensor_info);
+    }
+    update_channel_agreement(&sensor_infos);
+    Ok(())
+}
+
+/// Applies the EPA's humidity correction to a raw CF=1 PM2.5 reading, clamping the result to 0
+/// since the correction can otherwise go negative at low concentrations and high humidity.
+fn epa_correct_pm2_5(pm2_5_cf_1: f64, humidity: f64) -> f64 {
+    (0.524 * pm2_5_cf_1 - 0.0862 * humidity + 5.75).max(0f64)
+}
+
+/// Updates every gauge for a single sensor. Each field is parsed and recorded independently, so a
+/// single unparseable field only drops that field's gauge instead of the rest of the sensor.
+fn update_gauges_for_sensor(sensor_info: &SensorInfo) {
+    let id = sensor_info.id_string();
+    let common_labels: &[&str] = &[&id, &sensor_info.label];
+    let record_error = |e: ScrapeError| {
+        warn!("{}", e);
+        let reason = match &e {
+            ScrapeError::FieldParse { field, .. } => *field,
+            ScrapeError::Http(_) => "http",
+            ScrapeError::Json(_) => "json",
+        };
+        SCRAPE_ERRORS.with_label_values(&[&id, reason]).inc();
+    };
+
+    if let Some(ref uptime) = sensor_info.uptime {
+        match parse_i64(&id, "uptime", uptime) {
+            Ok(v) => UPTIME.with_label_values(common_labels).set(v),
+            Err(e) => record_error(e),
+        }
+    }
+    LAST_SEEN_TIMESTAMP
+        .with_label_values(common_labels)
+        .set(sensor_info.last_seen);
+    INFO.with_label_values(&[
+        &id,
+        &sensor_info.label,
+        format!("{}", sensor_info.lat).as_str(),
+        format!("{}", sensor_info.lon).as_str(),
+    ])
+    .set(1);
+
+    match parse_f64(&id, "pm_2_5_value", &sensor_info.pm_2_5_value) {
+        Ok(pm_2_5_value) => {
+            PM2_5_VALUE.with_label_values(common_labels).set(pm_2_5_value);
+            PM2_5_AQI
+                .with_label_values(common_labels)
+                .set(pm2_5_aqi_estimate(pm_2_5_value) as i64);
+        }
+        Err(e) => record_error(e),
+    }
+
+    if let Some(ref temp_f) = sensor_info.temp_f {
+        match parse_f64(&id, "temp_f", temp_f) {
+            Ok(v) => TEMP.with_label_values(common_labels).set(v),
+            Err(e) => record_error(e),
+        }
+    }
+    if let Some(ref humidity) = sensor_info.humidity {
+        match parse_f64(&id, "humidity", humidity) {
+            Ok(humidity) => {
+                HUMIDITY.with_label_values(common_labels).set(humidity);
+                match parse_f64(&id, "pm2_5_cf_1", &sensor_info.pm2_5_cf_1) {
+                    Ok(pm2_5_cf_1) => {
+                        let corrected = epa_correct_pm2_5(pm2_5_cf_1, humidity);
+                        PM2_5_CORRECTED_VALUE
                             .with_label_values(common_labels)
-                            .set(humidity.parse::<f64>()?);
-                    }
-                    if let Some(pressure) = sensor_info.pressure {
-                        PRESSURE
+                            .set(corrected);
+                        PM2_5_CORRECTED_AQI
                             .with_label_values(common_labels)
-                            .set(pressure.parse::<f64>()?);
+                            .set(pm2_5_aqi_estimate(corrected) as i64);
                     }
+                    Err(e) => record_error(e),
                 }
             }
+            Err(e) => record_error(e),
         }
-        None => warn!("results array not found!"),
-    };
-    Ok(())
+    }
+    if let Some(ref pressure) = sensor_info.pressure {
+        match parse_f64(&id, "pressure", pressure) {
+            Ok(v) => PRESSURE.with_label_values(common_labels).set(v),
+            Err(e) => record_error(e),
+        }
+    }
 }
 
 async fn metrics() -> Result<String, StatusCode> {
     info!("Handling metrics call");
-    let sensor_ids = env::var("PURPLEAIR_SENSOR_IDS").map_err(log_error)?;
-    scrape_purple_air(&sensor_ids)
-        .await
-        .map_err(log_box_error)?;
     let encoder = TextEncoder::new();
     let mut buffer = vec![];
     encoder
@@ -208,11 +634,6 @@ async fn metrics() -> Result<String, StatusCode> {
     Ok(prom_metrics)
 }
 
-fn log_box_error(err: Box<dyn std::error::Error>) -> StatusCode {
-    error!("{:?}", err);
-    StatusCode::INTERNAL_SERVER_ERROR
-}
-
 fn log_error<E>(err: E) -> StatusCode
 where
     E: std::error::Error,
@@ -300,4 +721,19 @@ mod tests {
         assert_eq!(pm2_5_aqi_estimate(550f64), 501);
         assert_eq!(pm2_5_aqi_estimate(900f64), 503);
     }
+
+    #[test]
+    fn test_epa_correct_pm2_5() {
+        assert!((epa_correct_pm2_5(20f64, 50f64) - 11.92f64).abs() < 1e-9);
+        // High humidity relative to a low raw reading drives the correction negative; it should
+        // be clamped to 0 rather than reported as a negative concentration.
+        assert_eq!(epa_correct_pm2_5(0f64, 100f64), 0f64);
+    }
+
+    #[test]
+    fn test_channel_percent_diff() {
+        assert!((channel_percent_diff(10f64, 12f64) - 18.181818181818183f64).abs() < 1e-9);
+        // An average of 0 is treated as perfect agreement rather than a division by zero.
+        assert_eq!(channel_percent_diff(0f64, 0f64), 0f64);
+    }
 }